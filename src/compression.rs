@@ -0,0 +1,58 @@
+use std::io::Write;
+
+use crate::log::BodyEncoding;
+use crate::PlaybackResult;
+
+/// Encodes `body` per `encoding`, the form it should take on the wire.
+pub fn encode(body: &str, encoding: BodyEncoding) -> PlaybackResult<Vec<u8>> {
+    match encoding {
+        BodyEncoding::Identity => Ok(body.as_bytes().to_vec()),
+        BodyEncoding::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(body.as_bytes())?;
+            Ok(encoder.finish()?)
+        }
+        BodyEncoding::Br => {
+            let mut output = Vec::new();
+            let mut writer = brotli::CompressorWriter::new(&mut output, 4096, 5, 22);
+            writer.write_all(body.as_bytes())?;
+            drop(writer);
+            Ok(output)
+        }
+    }
+}
+
+/// Ratio of encoded size to original size; `1.0` means no reduction, lower is smaller on the wire.
+pub fn ratio(original_bytes: usize, encoded_bytes: usize) -> Option<f64> {
+    if original_bytes == 0 {
+        return None;
+    }
+
+    Some(encoded_bytes as f64 / original_bytes as f64)
+}
+
+#[test]
+fn test_encode_identity_is_passthrough() {
+    let encoded = encode("hello world", BodyEncoding::Identity).unwrap();
+
+    assert_eq!(encoded, b"hello world");
+}
+
+#[test]
+fn test_encode_gzip_round_trips() {
+    use std::io::Read;
+
+    let encoded = encode("hello world", BodyEncoding::Gzip).unwrap();
+
+    let mut decoder = flate2::read::GzDecoder::new(&encoded[..]);
+    let mut decoded = String::new();
+    decoder.read_to_string(&mut decoded).unwrap();
+
+    assert_eq!(decoded, "hello world");
+}
+
+#[test]
+fn test_ratio() {
+    assert_eq!(ratio(100, 40), Some(0.4));
+    assert_eq!(ratio(0, 0), None);
+}
@@ -0,0 +1,326 @@
+use anyhow::bail;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{Method, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use crate::PlaybackResult;
+
+pub type Logs = Vec<Log>;
+
+#[derive(Debug, Clone)]
+pub struct Log {
+    pub accessed_at: DateTime<Utc>,
+    pub url: Url,
+    pub http_method: Method,
+    pub http_header: HashMap<String, String>,
+    pub http_body: String,
+    pub body_encoding: BodyEncoding,
+}
+
+/// How `http_body` should be encoded on the wire when the request is replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyEncoding {
+    Identity,
+    Gzip,
+    Br,
+}
+
+impl BodyEncoding {
+    /// Value to send in the `Content-Encoding` header, if any.
+    pub fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            BodyEncoding::Identity => None,
+            BodyEncoding::Gzip => Some("gzip"),
+            BodyEncoding::Br => Some("br"),
+        }
+    }
+}
+
+impl FromStr for BodyEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "identity" => Ok(BodyEncoding::Identity),
+            "gzip" => Ok(BodyEncoding::Gzip),
+            "br" => Ok(BodyEncoding::Br),
+            _ => bail!("unknown body encoding: {}", s),
+        }
+    }
+}
+
+type JsonLogs = Vec<JsonLog>;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct JsonLog {
+    accessed_at: String,
+    url: String,
+    http_method: String,
+    http_header: HashMap<String, String>,
+    http_body: String,
+    #[serde(default)]
+    body_encoding: Option<String>,
+}
+
+/// Parses a `JsonLog::accessed_at` value, which may carry an explicit offset
+/// (`%z`, e.g. `+0900`) or, for backward compatibility with recordings made
+/// before offsets were supported, the literal suffix `UTC`.
+fn parse_json_accessed_at(s: &str) -> anyhow::Result<DateTime<Utc>> {
+    const OFFSET_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f %z";
+    if let Ok(dt) = DateTime::parse_from_str(s, OFFSET_FORMAT) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+
+    const UTC_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f UTC";
+    match NaiveDateTime::parse_from_str(s, UTC_FORMAT) {
+        Ok(dt) => Ok(DateTime::<Utc>::from_utc(dt, Utc)),
+        Err(e) => bail!("Date and time format is not correct: {}", e),
+    }
+}
+
+impl TryFrom<JsonLog> for Log {
+    type Error = anyhow::Error;
+
+    fn try_from(json_log: JsonLog) -> Result<Self, Self::Error> {
+        let accessed_at = parse_json_accessed_at(&json_log.accessed_at)?;
+
+        let url = match reqwest::Url::parse(&json_log.url) {
+            Err(e) => bail!("url format is not correct: {}", e),
+            Ok(url) => url,
+        };
+
+        let http_method = match Method::from_bytes(&json_log.http_method.as_bytes()) {
+            Err(e) => bail!("Method is not correct: {}", e),
+            Ok(url) => url,
+        };
+
+        let http_header = json_log.http_header;
+        let http_body = json_log.http_body;
+        let body_encoding = match json_log.body_encoding {
+            Some(encoding) => BodyEncoding::from_str(&encoding)?,
+            None => BodyEncoding::Identity,
+        };
+
+        Ok(Log {
+            accessed_at,
+            url,
+            http_method,
+            http_header,
+            http_body,
+            body_encoding,
+        })
+    }
+}
+
+/// Shape of the input passed to [`resolve_log_text`]/[`resolve_log_file`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// This crate's bespoke JSON array format.
+    Json,
+    /// Apache/Nginx Common Log Format.
+    Clf,
+    /// Apache/Nginx Combined Log Format (CLF plus referer/user-agent).
+    Combined,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(LogFormat::Json),
+            "clf" => Ok(LogFormat::Clf),
+            "combined" => Ok(LogFormat::Combined),
+            _ => bail!("unknown log format: {}", s),
+        }
+    }
+}
+
+// host ident authuser [day/month/year:HH:MM:SS +zzzz] "METHOD path HTTP/x.y" status bytes
+// optionally followed by "referer" "user-agent" for Combined Log Format.
+static ACCESS_LOG_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"^(?P<host>\S+) \S+ \S+ \[(?P<time>[^\]]+)\] "(?P<method>\S+) (?P<path>\S+)(?: [^"]+)?" (?P<status>\d+|-) (?P<bytes>\d+|-)(?: "(?P<referer>[^"]*)" "(?P<user_agent>[^"]*)")?$"#,
+    )
+    .unwrap()
+});
+
+const ACCESS_LOG_TIME_FORMAT: &str = "%d/%b/%Y:%H:%M:%S %z";
+
+fn parse_access_log_line(line: &str, format: LogFormat, base_host: &str) -> PlaybackResult<Log> {
+    let captures = match ACCESS_LOG_RE.captures(line) {
+        Some(captures) => captures,
+        // `bail!` would produce an `anyhow::Error`, which doesn't coerce into
+        // the crate-wide `Box<dyn Error>` on a bare `return` (only `?` does).
+        None => {
+            return Err(anyhow::anyhow!("line does not match access log format: {}", line).into())
+        }
+    };
+
+    let accessed_at =
+        DateTime::parse_from_str(&captures["time"], ACCESS_LOG_TIME_FORMAT)?.with_timezone(&Utc);
+
+    let http_method = Method::from_bytes(captures["method"].as_bytes())?;
+
+    let url = Url::parse(&format!("http://{}{}", base_host, &captures["path"]))?;
+
+    let mut http_header = HashMap::new();
+    if format == LogFormat::Combined {
+        if let Some(referer) = captures.name("referer") {
+            if referer.as_str() != "-" {
+                http_header.insert("Referer".to_string(), referer.as_str().to_string());
+            }
+        }
+        if let Some(user_agent) = captures.name("user_agent") {
+            if user_agent.as_str() != "-" {
+                http_header.insert("User-Agent".to_string(), user_agent.as_str().to_string());
+            }
+        }
+    }
+
+    Ok(Log {
+        accessed_at,
+        url,
+        http_method,
+        http_header,
+        http_body: String::new(),
+        body_encoding: BodyEncoding::Identity,
+    })
+}
+
+/// Guesses whether `log_text` is Combined or Common Log Format by checking
+/// whether its first non-blank line carries the trailing referer/user-agent fields.
+fn detect_access_log_format(log_text: &str) -> LogFormat {
+    let first_line = log_text.lines().find(|line| !line.trim().is_empty());
+
+    match first_line.and_then(|line| ACCESS_LOG_RE.captures(line)) {
+        Some(captures) if captures.name("user_agent").is_some() => LogFormat::Combined,
+        _ => LogFormat::Clf,
+    }
+}
+
+fn resolve_json_log_text(log_text: &str) -> PlaybackResult<Logs> {
+    let json_logs: JsonLogs = serde_json::from_str(log_text)?;
+
+    let mut logs = vec![];
+    for json_log in json_logs {
+        let log = Log::try_from(json_log)?;
+
+        logs.push(log)
+    }
+
+    Ok(logs)
+}
+
+/// Parses `log_text` as Common/Combined Log Format, one entry per line.
+///
+/// Lines that fail to parse are reported to stderr and skipped rather than
+/// aborting the whole run.
+fn resolve_access_log_text(log_text: &str, format: LogFormat, base_host: &str) -> Logs {
+    let mut logs = vec![];
+
+    for (line_number, line) in log_text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_access_log_line(line, format, base_host) {
+            Ok(log) => logs.push(log),
+            Err(e) => eprintln!("\x1b[01;31mError:\x1b[m line {}: {}", line_number + 1, e),
+        }
+    }
+
+    logs
+}
+
+pub fn resolve_log_file(
+    log_file_path: &str,
+    format: Option<LogFormat>,
+    base_host: &str,
+) -> PlaybackResult<Logs> {
+    let log_text = std::fs::read_to_string(log_file_path)?;
+
+    resolve_log_text(&log_text, format, base_host)
+}
+
+pub fn resolve_log_text(
+    log_text: &str,
+    format: Option<LogFormat>,
+    base_host: &str,
+) -> PlaybackResult<Logs> {
+    match format {
+        Some(LogFormat::Json) => resolve_json_log_text(log_text),
+        Some(format) => Ok(resolve_access_log_text(log_text, format, base_host)),
+        None => match resolve_json_log_text(log_text) {
+            Ok(logs) => Ok(logs),
+            Err(_) => {
+                let format = detect_access_log_format(log_text);
+                Ok(resolve_access_log_text(log_text, format, base_host))
+            }
+        },
+    }
+}
+
+#[test]
+fn test_parse_json_accessed_at_with_explicit_offset() {
+    let dt = parse_json_accessed_at("2020-01-02 09:00:00.000 +0900").unwrap();
+
+    assert_eq!(dt, DateTime::parse_from_rfc3339("2020-01-02T00:00:00Z").unwrap());
+}
+
+#[test]
+fn test_parse_json_accessed_at_with_legacy_utc_suffix() {
+    let dt = parse_json_accessed_at("2020-01-02 00:00:00.000 UTC").unwrap();
+
+    assert_eq!(dt, DateTime::parse_from_rfc3339("2020-01-02T00:00:00Z").unwrap());
+}
+
+#[test]
+fn test_resolve_log_text() {
+    let sample_json = include_str!("../log_examples/sample.json");
+
+    assert!(resolve_log_text(sample_json, Some(LogFormat::Json), "localhost").is_ok());
+}
+
+#[test]
+fn test_resolve_log_text_clf() {
+    let line = r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 +0000] "GET /apache_pb.gif HTTP/1.0" 200 2326"#;
+
+    let logs = resolve_log_text(line, Some(LogFormat::Clf), "example.com").unwrap();
+
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].http_method, Method::GET);
+    assert_eq!(logs[0].url.as_str(), "http://example.com/apache_pb.gif");
+    assert!(logs[0].http_header.is_empty());
+}
+
+#[test]
+fn test_resolve_log_text_combined() {
+    let line = r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 +0000] "GET /apache_pb.gif HTTP/1.0" 200 2326 "http://www.example.com/start.html" "Mozilla/4.08 [en] (Win98; I ;Nav)""#;
+
+    let logs = resolve_log_text(line, Some(LogFormat::Combined), "example.com").unwrap();
+
+    assert_eq!(logs.len(), 1);
+    assert_eq!(
+        logs[0].http_header.get("Referer").map(String::as_str),
+        Some("http://www.example.com/start.html")
+    );
+    assert_eq!(
+        logs[0].http_header.get("User-Agent").map(String::as_str),
+        Some("Mozilla/4.08 [en] (Win98; I ;Nav)")
+    );
+}
+
+#[test]
+fn test_resolve_log_text_skips_unparseable_lines() {
+    let text = "this is not a valid access log line\n127.0.0.1 - frank [10/Oct/2000:13:55:36 +0000] \"GET /ok HTTP/1.0\" 200 2326";
+
+    let logs = resolve_log_text(text, Some(LogFormat::Clf), "example.com").unwrap();
+
+    assert_eq!(logs.len(), 1);
+}
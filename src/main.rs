@@ -1,14 +1,24 @@
-use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use anyhow::bail;
+use chrono::Duration;
 use clap::{App, AppSettings, Arg};
-use reqwest::{Method, Url};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::convert::TryInto;
 use std::str::FromStr;
 use std::time;
 use tokio::task;
 use tokio::time::delay_for;
 
+mod compression;
+mod log;
+mod report;
+
+use log::{Log, LogFormat, Logs};
+use report::RequestOutcome;
+use reqwest::Client;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore};
+
+const DEFAULT_MAX_CONCURRENCY: usize = 10_000;
+
 type PlaybackResult<T> = std::result::Result<T, PlaybackError>;
 type PlaybackError = Box<dyn std::error::Error>;
 
@@ -25,76 +35,254 @@ async fn main() -> PlaybackResult<()> {
         std::process::exit(1)
     }
 
+    let format = matches
+        .value_of("format")
+        .map(LogFormat::from_str)
+        .transpose()?;
+    let host = matches.value_of("host").unwrap_or("localhost");
+
     let logs = if let Some(path) = filepath {
-        resolve_log_file(path)
+        log::resolve_log_file(path, format, host)
     } else if let Some(text) = access_log {
-        resolve_log_text(text)
+        log::resolve_log_text(text, format, host)
     } else {
         println!("\x1b[01;31mError:\x1b[m please specify log filepath or access log text");
         std::process::exit(1)
     }
     .unwrap();
 
-    let shift = matches.value_of("shift").unwrap_or("0s");
-    let shift_time = parse_time(shift)?;
-
-    // TODO 新しいstructを作る
-    // struct Hoge {
-    //  request_time: time::Instant,
-    //  request: Request
-    // }
-    // 的なやつ
-    let shifted_logs = logs
-        .iter()
-        .map(|log| Log {
-            accessed_at: log.accessed_at + Duration::from_std(shift_time).unwrap(),
-            url: log.url.clone(),
-            http_method: log.http_method.clone(),
-            http_header: log.http_header.clone(),
-            http_body: log.http_body.clone(),
-        })
-        .collect();
-
-    send_requests(shifted_logs).await?;
+    let scheduled_logs = apply_replay_timing(logs, &matches)?;
+
+    let client = build_client(&matches)?;
+    let max_concurrency = matches
+        .value_of("max-concurrency")
+        .map(str::parse)
+        .transpose()?
+        .unwrap_or(DEFAULT_MAX_CONCURRENCY);
+
+    let outcomes = send_requests(client, max_concurrency, scheduled_logs).await?;
+
+    let report = report::build_report(&outcomes);
+    report::emit_report(&report, matches.value_of("report"))?;
 
     Ok(())
 }
 
-async fn send_requests(logs: Logs) -> PlaybackResult<()> {
-    println!("start {:?}", logs);
+/// Rewrites each log's `accessed_at` into the time it should actually be replayed at.
+///
+/// With `--shift`, every entry is moved by the same fixed offset (the original
+/// absolute-time behavior). Otherwise logs are replayed relative to playback
+/// start: the earliest entry fires immediately and later entries keep their
+/// original spacing, compressed or stretched by `--rate`.
+fn apply_replay_timing(mut logs: Logs, matches: &clap::ArgMatches) -> PlaybackResult<Logs> {
+    if let Some(shift) = matches.value_of("shift") {
+        let shift_time = Duration::from_std(parse_time(shift)?).unwrap();
+        for log in logs.iter_mut() {
+            log.accessed_at += shift_time;
+        }
+
+        return Ok(logs);
+    }
+
+    let rate: f64 = matches
+        .value_of("rate")
+        .map(str::parse)
+        .transpose()?
+        .unwrap_or(1.0);
+    if rate <= 0.0 {
+        // Not `bail!`: `PlaybackResult` is `Box<dyn Error>`, and only `?` converts
+        // the `anyhow::Error` it produces — a bare `return` would not.
+        return Err(anyhow::anyhow!("--rate must be greater than 0").into());
+    }
+
+    logs.sort_by_key(|log| log.accessed_at);
 
-    // TODO Add async task budget
-    // const MAX_REQUEST: usize = 10_000;
+    let t0 = match logs.first() {
+        Some(log) => log.accessed_at,
+        None => return Ok(logs),
+    };
+    let playback_start = chrono::Utc::now();
+
+    for log in logs.iter_mut() {
+        let elapsed_ms = (log.accessed_at - t0).num_milliseconds() as f64 / rate;
+        log.accessed_at = playback_start + Duration::milliseconds(elapsed_ms as i64);
+    }
+
+    Ok(logs)
+}
+
+#[cfg(test)]
+fn test_log(accessed_at: chrono::DateTime<chrono::Utc>) -> Log {
+    Log {
+        accessed_at,
+        url: reqwest::Url::parse("http://example.com").unwrap(),
+        http_method: reqwest::Method::GET,
+        http_header: std::collections::HashMap::new(),
+        http_body: String::new(),
+        body_encoding: log::BodyEncoding::Identity,
+    }
+}
+
+#[test]
+fn test_apply_replay_timing_anchors_and_scales_by_rate() {
+    let t0 = chrono::Utc::now();
+    let logs = vec![test_log(t0), test_log(t0 + Duration::seconds(10))];
+
+    let matches = build_app().get_matches_from(vec!["playback", "--rate", "2"]);
+    let before = chrono::Utc::now();
+    let scheduled = apply_replay_timing(logs, &matches).unwrap();
+    let after = chrono::Utc::now();
+
+    assert!(scheduled[0].accessed_at >= before && scheduled[0].accessed_at <= after);
+    let gap = scheduled[1].accessed_at - scheduled[0].accessed_at;
+    assert_eq!(gap.num_milliseconds(), 5_000);
+}
+
+#[test]
+fn test_apply_replay_timing_rejects_non_positive_rate() {
+    let logs = vec![test_log(chrono::Utc::now())];
+    let matches = build_app().get_matches_from(vec!["playback", "--rate", "0"]);
+
+    assert!(apply_replay_timing(logs, &matches).is_err());
+}
+
+fn build_client(matches: &clap::ArgMatches) -> PlaybackResult<Client> {
+    let mut builder = Client::builder()
+        .gzip(true)
+        .brotli(true)
+        .danger_accept_invalid_certs(matches.is_present("insecure"));
+
+    if let Some(timeout) = matches.value_of("timeout") {
+        builder = builder.timeout(parse_time(timeout)?);
+    }
+
+    if let Some(pool_max_idle_per_host) = matches.value_of("pool-max-idle-per-host") {
+        builder = builder.pool_max_idle_per_host(pool_max_idle_per_host.parse()?);
+    }
+
+    Ok(builder.build()?)
+}
+
+async fn send_requests(
+    client: Client,
+    max_concurrency: usize,
+    logs: Logs,
+) -> PlaybackResult<Vec<RequestOutcome>> {
+    let semaphore = Arc::new(Semaphore::new(max_concurrency));
+    let (sender, mut receiver) = mpsc::unbounded_channel();
 
     let mut tasks = vec![];
     for log in logs {
+        let client = client.clone();
+        let semaphore = semaphore.clone();
+        let sender = sender.clone();
         let task = task::spawn(async move {
-            schedule_request(log).await.unwrap();
+            schedule_request(client, semaphore, log, sender).await.unwrap();
         });
         tasks.push(task);
     }
+    drop(sender);
 
     for task in tasks {
         task.await.unwrap();
     }
 
-    Ok(())
-}
-
-async fn schedule_request(log: Log) -> PlaybackResult<()> {
-    let duration = (log.accessed_at - chrono::Utc::now()).to_std()?;
+    let mut outcomes = vec![];
+    while let Some(outcome) = receiver.recv().await {
+        outcomes.push(outcome);
+    }
 
-    // TODO debug log
-    println!("schedule for {:?}", duration);
+    Ok(outcomes)
+}
 
-    delay_for(duration).await;
+async fn schedule_request(
+    client: Client,
+    semaphore: Arc<Semaphore>,
+    log: Log,
+    outcome_sender: mpsc::UnboundedSender<RequestOutcome>,
+) -> PlaybackResult<()> {
+    // A log scheduled for "now" will already be slightly in the past by the
+    // time this task runs (startup and scheduling jitter); treat that as
+    // "send immediately" rather than propagating the resulting negative
+    // duration as an error.
+    let scheduled_offset = (log.accessed_at - chrono::Utc::now())
+        .to_std()
+        .unwrap_or_default();
+
+    delay_for(scheduled_offset).await;
+
+    // Acquiring the permit after the delay keeps the intended schedule intact;
+    // only the in-flight fan-out is bounded, not when a request becomes due.
+    let _permit = semaphore.acquire().await;
+
+    let request_body_bytes = log.http_body.len();
+    let encoded_body = compression::encode(&log.http_body, log.body_encoding)?;
+    let request_encoded_bytes = encoded_body.len();
 
     let mut request = reqwest::Request::new(log.http_method, log.url);
-    *request.body_mut() = Some(log.http_body.into());
     *request.headers_mut() = (&log.http_header).try_into().unwrap();
-
-    let response = reqwest::Client::new().execute(request).await;
-    println!("{:?}", response);
+    if let Some(content_encoding) = log.body_encoding.content_encoding() {
+        request.headers_mut().insert(
+            reqwest::header::CONTENT_ENCODING,
+            reqwest::header::HeaderValue::from_static(content_encoding),
+        );
+    }
+    request.headers_mut().insert(
+        reqwest::header::CONTENT_LENGTH,
+        reqwest::header::HeaderValue::from_str(&request_encoded_bytes.to_string()).unwrap(),
+    );
+    *request.body_mut() = Some(encoded_body.into());
+
+    let sent_at = chrono::Utc::now();
+    let start = time::Instant::now();
+    let response = client.execute(request).await;
+    let latency = start.elapsed();
+
+    // Reading the body rather than trusting `Content-Length` matters twice over:
+    // reqwest strips that header once it transparently gzip/brotli-decodes a
+    // response, so it's the only way to see the decompressed size; reading it
+    // to completion is also what lets the connection return to the client's pool.
+    let outcome = match response {
+        Ok(response) => {
+            let status = Some(response.status().as_u16());
+            match response.bytes().await {
+                Ok(body) => RequestOutcome {
+                    scheduled_offset,
+                    sent_at,
+                    latency,
+                    status,
+                    response_bytes: Some(body.len() as u64),
+                    request_body_bytes,
+                    request_encoded_bytes,
+                    error: None,
+                },
+                Err(e) => RequestOutcome {
+                    scheduled_offset,
+                    sent_at,
+                    latency,
+                    status,
+                    response_bytes: None,
+                    request_body_bytes,
+                    request_encoded_bytes,
+                    error: Some(e.to_string()),
+                },
+            }
+        }
+        Err(e) => RequestOutcome {
+            scheduled_offset,
+            sent_at,
+            latency,
+            status: None,
+            response_bytes: None,
+            request_body_bytes,
+            request_encoded_bytes,
+            error: Some(e.to_string()),
+        },
+    };
+
+    // The receiving end is dropped once every task has finished reporting;
+    // a send failing here just means no one is listening any more.
+    let _ = outcome_sender.send(outcome);
 
     Ok(())
 }
@@ -115,99 +303,61 @@ fn build_app() -> App<'static, 'static> {
         )
         .arg(
             Arg::with_name("shift")
-                .help("time shift (example 2s, 5m, 5h, 1d, 2w")
+                .help("Replay at a fixed absolute offset instead of relative timing (example 2s, 5m, 5h, 1d, 2w)")
                 .long("shift")
                 .value_name("shift"),
         )
+        .arg(
+            Arg::with_name("rate")
+                .help("Speed multiplier for relative replay timing (2 = twice as fast, 0.5 = half speed)")
+                .long("rate")
+                .value_name("factor"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .help("Input log format (json, clf, combined); auto-detected if omitted")
+                .long("format")
+                .value_name("format"),
+        )
+        .arg(
+            Arg::with_name("host")
+                .help("Base host used to build absolute URLs for clf/combined logs")
+                .long("host")
+                .value_name("host"),
+        )
+        .arg(
+            Arg::with_name("report")
+                .help("Write the replay report to this path instead of stdout")
+                .long("report")
+                .value_name("path"),
+        )
+        .arg(
+            Arg::with_name("max-concurrency")
+                .help("Maximum number of requests in flight at once")
+                .long("max-concurrency")
+                .value_name("n"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .help("Per-request timeout (example 2s, 5m, 5h, 1d, 2w)")
+                .long("timeout")
+                .value_name("timeout"),
+        )
+        .arg(
+            Arg::with_name("insecure")
+                .help("Disable TLS certificate verification")
+                .long("insecure"),
+        )
+        .arg(
+            Arg::with_name("pool-max-idle-per-host")
+                .help("Maximum idle connections to keep pooled per host")
+                .long("pool-max-idle-per-host")
+                .value_name("n"),
+        )
         .setting(AppSettings::DeriveDisplayOrder)
         .setting(AppSettings::ColoredHelp)
 }
 
-type Logs = Vec<Log>;
-
-#[derive(Debug)]
-struct Log {
-    accessed_at: DateTime<Utc>,
-    url: Url,
-    http_method: Method,
-    http_header: HashMap<String, String>,
-    http_body: String,
-}
-
-type JsonLogs = Vec<JsonLog>;
-
-#[derive(Serialize, Deserialize, Debug)]
-struct JsonLog {
-    accessed_at: String,
-    url: String,
-    http_method: String,
-    http_header: HashMap<String, String>,
-    http_body: String,
-}
-
-use std::convert::TryFrom;
-
-impl TryFrom<JsonLog> for Log {
-    type Error = anyhow::Error;
-
-    fn try_from(json_log: JsonLog) -> Result<Self, Self::Error> {
-        const FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f UTC"; // TODO timezoneがUTCじゃなくても使えるようにする
-        let dt = match NaiveDateTime::parse_from_str(&json_log.accessed_at, FORMAT) {
-            Err(e) => bail!("Date and time format is not correct: {}", e),
-            Ok(dt) => dt,
-        };
-
-        let accessed_at = DateTime::<Utc>::from_utc(dt, Utc);
-
-        let url = match reqwest::Url::parse(&json_log.url) {
-            Err(e) => bail!("url format is not correct: {}", e),
-            Ok(url) => url,
-        };
-
-        let http_method = match Method::from_bytes(&json_log.http_method.as_bytes()) {
-            Err(e) => bail!("Method is not correct: {}", e),
-            Ok(url) => url,
-        };
-
-        let http_header = json_log.http_header;
-        let http_body = json_log.http_body;
-
-        Ok(Log {
-            accessed_at,
-            url,
-            http_method,
-            http_header,
-            http_body,
-        })
-    }
-}
-
-fn resolve_log_file(log_file_path: &str) -> PlaybackResult<Logs> {
-    let log_text = std::fs::read_to_string(log_file_path)?;
-
-    resolve_log_text(&log_text)
-}
-
-fn resolve_log_text(log_text: &str) -> PlaybackResult<Logs> {
-    let json_logs: JsonLogs = serde_json::from_str(log_text)?;
-
-    let mut logs = vec![];
-    for json_log in json_logs {
-        let log = Log::try_from(json_log)?;
-
-        logs.push(log)
-    }
-
-    Ok(logs)
-}
-
-#[test]
-fn test_resolve_log_text() {
-    let sample_json = include_str!("../log_examples/sample.json");
-
-    assert!(resolve_log_text(sample_json).is_ok());
-}
-
 #[derive(Eq, PartialEq, Debug)]
 enum TimeType {
     S(u64),
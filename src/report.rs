@@ -0,0 +1,164 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::PlaybackResult;
+
+/// Outcome of a single replayed request, reported back by a `schedule_request` task.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestOutcome {
+    /// Offset from playback start the request was scheduled for.
+    pub scheduled_offset: Duration,
+    /// Wall-clock time the request was actually sent.
+    pub sent_at: DateTime<Utc>,
+    /// Time spent waiting for a response.
+    pub latency: Duration,
+    /// Status code, if the request completed.
+    pub status: Option<u16>,
+    /// Size of the response body in bytes, if the request completed.
+    pub response_bytes: Option<u64>,
+    /// Size of the request body before `body_encoding` was applied.
+    pub request_body_bytes: usize,
+    /// Size of the request body actually sent over the wire.
+    pub request_encoded_bytes: usize,
+    /// Transport-level error, if the request did not complete.
+    pub error: Option<String>,
+}
+
+impl RequestOutcome {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencyReport {
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub status_counts: HashMap<u16, usize>,
+    pub latency: Option<LatencyReport>,
+    /// Total request-body bytes actually sent over the wire divided by total
+    /// bytes before encoding, pooled across all requests (so a few large
+    /// bodies dominate the ratio over many small ones). `None` if every body
+    /// was empty.
+    pub compression_ratio: Option<f64>,
+}
+
+/// Aggregates raw request outcomes into a summary report.
+pub fn build_report(outcomes: &[RequestOutcome]) -> Report {
+    let total = outcomes.len();
+    let succeeded = outcomes.iter().filter(|o| o.succeeded()).count();
+    let failed = total - succeeded;
+
+    let mut status_counts = HashMap::new();
+    for status in outcomes.iter().filter_map(|o| o.status) {
+        *status_counts.entry(status).or_insert(0) += 1;
+    }
+
+    let mut latencies_ms: Vec<f64> = outcomes
+        .iter()
+        .filter(|o| o.succeeded())
+        .map(|o| o.latency.as_secs_f64() * 1000.0)
+        .collect();
+    latencies_ms.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let latency = if latencies_ms.is_empty() {
+        None
+    } else {
+        Some(LatencyReport {
+            min_ms: latencies_ms[0],
+            mean_ms: latencies_ms.iter().sum::<f64>() / latencies_ms.len() as f64,
+            p50_ms: percentile(&latencies_ms, 0.50),
+            p90_ms: percentile(&latencies_ms, 0.90),
+            p99_ms: percentile(&latencies_ms, 0.99),
+        })
+    };
+
+    let total_body_bytes: usize = outcomes.iter().map(|o| o.request_body_bytes).sum();
+    let total_encoded_bytes: usize = outcomes.iter().map(|o| o.request_encoded_bytes).sum();
+    let compression_ratio = crate::compression::ratio(total_body_bytes, total_encoded_bytes);
+
+    Report {
+        total,
+        succeeded,
+        failed,
+        status_counts,
+        latency,
+        compression_ratio,
+    }
+}
+
+/// `sorted` must already be sorted ascending.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p * (sorted.len() - 1) as f64).round() as usize;
+
+    sorted[rank]
+}
+
+#[cfg(feature = "report-yaml")]
+fn serialize_report(report: &Report) -> PlaybackResult<String> {
+    Ok(serde_yaml::to_string(report)?)
+}
+
+#[cfg(not(feature = "report-yaml"))]
+fn serialize_report(report: &Report) -> PlaybackResult<String> {
+    Ok(serde_json::to_string_pretty(report)?)
+}
+
+/// Writes `report` to `path`, or stdout if no path is given.
+pub fn emit_report(report: &Report, path: Option<&str>) -> PlaybackResult<()> {
+    let rendered = serialize_report(report)?;
+
+    match path {
+        Some(path) => std::fs::write(path, rendered)?,
+        None => println!("{}", rendered),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_build_report() {
+    let outcomes = vec![
+        RequestOutcome {
+            scheduled_offset: Duration::from_secs(0),
+            sent_at: Utc::now(),
+            latency: Duration::from_millis(100),
+            status: Some(200),
+            response_bytes: Some(12),
+            request_body_bytes: 100,
+            request_encoded_bytes: 40,
+            error: None,
+        },
+        RequestOutcome {
+            scheduled_offset: Duration::from_secs(1),
+            sent_at: Utc::now(),
+            latency: Duration::from_millis(200),
+            status: None,
+            response_bytes: None,
+            request_body_bytes: 0,
+            request_encoded_bytes: 0,
+            error: Some("connection refused".to_string()),
+        },
+    ];
+
+    let report = build_report(&outcomes);
+
+    assert_eq!(report.total, 2);
+    assert_eq!(report.succeeded, 1);
+    assert_eq!(report.failed, 1);
+    assert_eq!(report.status_counts.get(&200), Some(&1));
+    assert!(report.latency.is_some());
+    assert_eq!(report.compression_ratio, Some(0.4));
+}